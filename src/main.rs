@@ -1,10 +1,53 @@
-use std::{fs, io, iter, path::PathBuf, process, thread, time::Duration};
+use std::{
+    fs,
+    io::{self, Read},
+    os::unix::{io::AsRawFd, process::CommandExt},
+    path::PathBuf,
+    process,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 use clap::Parser;
+use nix::sys::signal::Signal;
+use regex::Regex;
 use tap::Pipe;
 use tracing::{debug, error, info};
 
+/// How often to poll a background child for exit while waiting out its grace period.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long to wait before re-draining a non-blocking pipe that had nothing ready.
+const STREAM_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How often to re-scan background task output for readiness probes.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A [`Signal`] that can be parsed from the command line or a config file by its
+/// name (e.g. `SIGINT`, `SIGTERM`, `SIGKILL`).
+#[derive(Debug, Clone, Copy)]
+struct SignalArg(Signal);
+
+impl FromStr for SignalArg {
+    type Err = nix::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<Signal>().map(SignalArg)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SignalArg {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct Meanwhile {
@@ -12,6 +55,183 @@ struct Meanwhile {
     args: Vec<String>,
     stdout_suffix: Option<String>,
     stderr_suffix: Option<String>,
+    /// Signal sent first to ask this task to stop; overrides `--stop-signal`.
+    stop_signal: Option<SignalArg>,
+    /// Seconds to wait for this task to exit before escalating; overrides `--grace-period`.
+    grace_period: Option<f64>,
+    /// Signal used to force this task to stop after the grace period; overrides `--force-signal`.
+    force_signal: Option<SignalArg>,
+    /// Forward this task's output to our stderr while capturing it; overrides `--tee`.
+    stream: Option<bool>,
+    /// Substring/regex to watch for in this task's output before starting the main task.
+    ready_when: Option<String>,
+    /// Seconds to wait for `ready-when` to match before giving up; overrides `--ready-timeout`.
+    ready_timeout: Option<f64>,
+    /// How to connect this task's stdout; defaults to `piped` (captured).
+    stdout: Option<StdioMode>,
+    /// How to connect this task's stderr; defaults to `piped` (captured).
+    stderr: Option<StdioMode>,
+    /// How to connect this task's stdin; defaults to `null`.
+    stdin: Option<StdioMode>,
+}
+
+/// How to connect one of a task's standard streams, mapping to the corresponding
+/// [`process::Stdio`] variant.
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StdioMode {
+    Inherit,
+    Piped,
+    Null,
+}
+
+impl StdioMode {
+    fn stdio(self) -> process::Stdio {
+        match self {
+            StdioMode::Inherit => process::Stdio::inherit(),
+            StdioMode::Piped => process::Stdio::piped(),
+            StdioMode::Null => process::Stdio::null(),
+        }
+    }
+
+    fn is_piped(self) -> bool {
+        matches!(self, StdioMode::Piped)
+    }
+}
+
+/// A spawned background task together with the state needed to shut it down and
+/// collect its output.
+struct Background {
+    cmd: String,
+    child: process::Child,
+    /// Process group id of the child (it leads its own group via `setsid`), so
+    /// signals can be delivered to the whole subtree it spawns.
+    pgid: i32,
+    stdout: Option<StreamCapture>,
+    stderr: Option<StreamCapture>,
+    stdout_suffix: Option<String>,
+    stderr_suffix: Option<String>,
+    stop_signal: Signal,
+    grace_period: Duration,
+    force_signal: Signal,
+    /// Pattern that must appear in this task's output before the main task runs.
+    ready_when: Option<Regex>,
+    ready_timeout: Duration,
+}
+
+impl Background {
+    /// Whether this task's `ready_when` pattern has appeared in either output
+    /// stream so far, scanning the buffers the forwarders are filling.
+    fn is_ready(&self, pattern: &Regex) -> bool {
+        let matches = |capture: &Option<StreamCapture>| {
+            capture
+                .as_ref()
+                .is_some_and(|c| output_matches(pattern, &c.snapshot()))
+        };
+        matches(&self.stdout) || matches(&self.stderr)
+    }
+}
+
+/// A reader thread draining one background pipe, accumulating everything it
+/// reads into a shared buffer and — when `tee` is set — forwarding each complete
+/// line to our stderr, prefixed with a label.
+///
+/// Modelled on a streaming stderr forwarder: the pipe is put into non-blocking
+/// mode and drained of whatever bytes are currently available, newline-split for
+/// labelled console output with any partial trailing line retained until the
+/// next read, and flushed on EOF. If the pipe can't be made non-blocking the
+/// thread simply blocks on `read`, which is harmless since it owns the pipe.
+struct StreamCapture {
+    buf: Arc<Mutex<Vec<u8>>>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl StreamCapture {
+    fn spawn<R: Read + AsRawFd + Send + 'static>(reader: R, label: String, tee: bool) -> Self {
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let handle = {
+            let buf = Arc::clone(&buf);
+            thread::spawn(move || forward(reader, &label, tee, &buf))
+        };
+        Self { buf, handle }
+    }
+
+    /// A copy of everything accumulated so far, without consuming the capture.
+    fn snapshot(&self) -> Vec<u8> {
+        self.buf.lock().map(|g| g.clone()).unwrap_or_default()
+    }
+
+    /// Join the reader thread and take the fully-accumulated output.
+    fn collect(self) -> Vec<u8> {
+        if self.handle.join().is_err() {
+            error!("output forwarder thread panicked");
+        }
+        match Arc::try_unwrap(self.buf) {
+            Ok(mutex) => mutex.into_inner().unwrap_or_else(|e| e.into_inner()),
+            Err(arc) => arc.lock().map(|g| g.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Drain `reader` until EOF, accumulating into `buf` and — when `tee` — emitting
+/// complete lines to stderr labelled with `label`.
+fn forward<R: Read + AsRawFd>(mut reader: R, label: &str, tee: bool, buf: &Arc<Mutex<Vec<u8>>>) {
+    if let Err(error) = set_nonblocking(&reader) {
+        debug!(?error, %label, "couldn't make pipe non-blocking, falling back to blocking reads");
+    }
+    let mut chunk = [0u8; 8192];
+    let mut emitted = 0;
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => {
+                let mut guard = buf.lock().unwrap();
+                guard.extend_from_slice(&chunk[..n]);
+                if tee {
+                    emitted = emit_lines(label, &guard, emitted, false);
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(STREAM_POLL_INTERVAL);
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => {
+                error!(?error, %label, "error reading background task output");
+                break;
+            }
+        }
+    }
+    if tee {
+        let guard = buf.lock().unwrap();
+        emit_lines(label, &guard, emitted, true);
+    }
+}
+
+/// Print every complete line in `buf[emitted..]` (and, when `flush`, any trailing
+/// partial line) prefixed with `label`, returning the new `emitted` offset.
+fn emit_lines(label: &str, buf: &[u8], mut emitted: usize, flush: bool) -> usize {
+    while let Some(nl) = buf[emitted..].iter().position(|&b| b == b'\n') {
+        eprintln!("{label} | {}", String::from_utf8_lossy(&buf[emitted..emitted + nl]));
+        emitted += nl + 1;
+    }
+    if flush && emitted < buf.len() {
+        eprintln!("{label} | {}", String::from_utf8_lossy(&buf[emitted..]));
+        emitted = buf.len();
+    }
+    emitted
+}
+
+fn set_nonblocking<F: AsRawFd>(f: &F) -> nix::Result<()> {
+    use nix::fcntl::{fcntl, FcntlArg, OFlag};
+    let fd = f.as_raw_fd();
+    let flags = OFlag::from_bits_truncate(fcntl(fd, FcntlArg::F_GETFL)?);
+    fcntl(fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+    Ok(())
+}
+
+/// Whether `pattern` matches anywhere in `bytes`, decoded lossily as UTF-8.
+fn output_matches(pattern: &Regex, bytes: &[u8]) -> bool {
+    pattern.is_match(&String::from_utf8_lossy(bytes))
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -28,6 +248,21 @@ struct Args {
     /// How long to sleep in seconds after starting the background tasks
     #[arg(short, long, default_value_t = 1.0)]
     sleep_after_spawn: f64,
+    /// Default signal sent to background tasks to ask them to stop
+    #[arg(long, default_value = "SIGINT")]
+    stop_signal: SignalArg,
+    /// Default seconds to wait for a background task to exit before force-killing it
+    #[arg(long, default_value_t = 1.0)]
+    grace_period: f64,
+    /// Default signal used to force-kill a background task after the grace period
+    #[arg(long, default_value = "SIGKILL")]
+    force_signal: SignalArg,
+    /// Stream background task output to our stderr (prefixed with the task's command) while still capturing it
+    #[arg(short = 't', long)]
+    tee: bool,
+    /// Default seconds to wait for a task's `ready-when` probe to match before giving up
+    #[arg(long, default_value_t = 30.0)]
+    ready_timeout: f64,
     /// The prefix to give to all output files
     #[arg(short, long)]
     name: Option<String>,
@@ -80,6 +315,13 @@ fn main() -> anyhow::Result<()> {
         })?;
     debug!(?meanwhile_file);
 
+    let (default_stop_signal, default_grace_period, default_force_signal, default_stream, default_ready_timeout) = (
+        args.stop_signal.0,
+        args.grace_period,
+        args.force_signal.0,
+        args.tee,
+        args.ready_timeout,
+    );
     let children = meanwhile_file
         .meanwhile
         .into_iter()
@@ -89,17 +331,89 @@ fn main() -> anyhow::Result<()> {
                  args,
                  stdout_suffix,
                  stderr_suffix,
+                 stop_signal,
+                 grace_period,
+                 force_signal,
+                 stream,
+                 ready_when,
+                 ready_timeout,
+                 stdout: stdout_mode,
+                 stderr: stderr_mode,
+                 stdin: stdin_mode,
              }| {
                 info!(%cmd, ?args, "spawning background task");
-                process::Command::new(&cmd)
+                let stdout_mode = stdout_mode.unwrap_or(StdioMode::Piped);
+                let stderr_mode = stderr_mode.unwrap_or(StdioMode::Piped);
+                let stdin_mode = stdin_mode.unwrap_or(StdioMode::Null);
+                let ready_when = ready_when
+                    .map(|pattern| {
+                        Regex::new(&pattern).with_context(|| {
+                            format!("invalid ready-when pattern for {cmd}: {pattern}")
+                        })
+                    })
+                    .transpose()?;
+                // A probe can only match output we actually capture; if both
+                // watched streams are left un-piped it could never match and
+                // would always hit ready-timeout, so reject that up front.
+                if ready_when.is_some() && !stdout_mode.is_piped() && !stderr_mode.is_piped() {
+                    anyhow::bail!(
+                        "task `{cmd}` has a ready-when probe but neither stdout nor stderr is piped"
+                    );
+                }
+                let ready_timeout =
+                    Duration::from_secs_f64(ready_timeout.unwrap_or(default_ready_timeout));
+                let mut command = process::Command::new(&cmd);
+                command
                     .args(args)
-                    .stdout(process::Stdio::piped())
-                    .stderr(process::Stdio::piped())
-                    .stdin(process::Stdio::null())
+                    .stdout(stdout_mode.stdio())
+                    .stderr(stderr_mode.stdio())
+                    .stdin(stdin_mode.stdio());
+                // Put the child in its own process group so grandchildren it
+                // spawns receive our shutdown signals instead of being orphaned.
+                unsafe {
+                    command.pre_exec(|| {
+                        nix::unistd::setsid()
+                            .map(drop)
+                            .map_err(|errno| io::Error::from_raw_os_error(errno as i32))
+                    });
+                }
+                command
                     .spawn()
-                    .map(|child| {
+                    .map(|mut child| {
                         debug!(pid = child.id());
-                        (child, stdout_suffix, stderr_suffix)
+                        // The child is a group leader, so its pgid equals its pid.
+                        let pgid = child.id() as i32;
+                        let tee = stream.unwrap_or(default_stream);
+                        let stdout = stdout_mode.is_piped().then(|| {
+                            StreamCapture::spawn(
+                                child.stdout.take().unwrap(),
+                                format!("{cmd} (stdout)"),
+                                tee,
+                            )
+                        });
+                        let stderr = stderr_mode.is_piped().then(|| {
+                            StreamCapture::spawn(
+                                child.stderr.take().unwrap(),
+                                format!("{cmd} (stderr)"),
+                                tee,
+                            )
+                        });
+                        Background {
+                            cmd: cmd.clone(),
+                            child,
+                            pgid,
+                            stdout,
+                            stderr,
+                            stdout_suffix,
+                            stderr_suffix,
+                            stop_signal: stop_signal.map_or(default_stop_signal, |s| s.0),
+                            grace_period: Duration::from_secs_f64(
+                                grace_period.unwrap_or(default_grace_period),
+                            ),
+                            force_signal: force_signal.map_or(default_force_signal, |s| s.0),
+                            ready_when,
+                            ready_timeout,
+                        }
                     })
                     .with_context(|| format!("couldn't spawn background task with command {}", cmd))
             },
@@ -110,56 +424,60 @@ fn main() -> anyhow::Result<()> {
     let cmd = cmd_and_args.remove(0);
     let cmd_args = cmd_and_args;
 
-    info!(
-        seconds = args.sleep_after_spawn,
-        "waiting before running main task"
-    );
-    thread::sleep(Duration::from_secs_f64(args.sleep_after_spawn));
-    info!(%cmd, ?cmd_args, "running main task");
-    // todo: handle ctrl+c, and still collect outputs
-    let output = process::Command::new(&cmd)
-        .args(cmd_args)
-        .output()
-        // OS will clean up our children
-        .with_context(|| format!("couldn't execute main task with command {cmd}"))?;
-
-    info!(?output, "main task exited");
-
-    for (child, _, _) in children.iter() {
-        debug!(pid = child.id(), "interrupting child");
-        if let Err(errno) = nix::sys::signal::kill(
-            nix::unistd::Pid::from_raw(child.id() as _),
-            nix::sys::signal::SIGINT,
-        ) {
-            error!(%errno, pid = child.id(), "couldn't interrupt child");
-        }
+    // Install the interrupt handler before the startup wait, so a Ctrl+C during
+    // the sleep or readiness probing still routes through shutdown-and-collect
+    // rather than killing us with default disposition — which would leave the
+    // setsid'd background children orphaned and leaking.
+    let caught = Arc::new(AtomicUsize::new(0));
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register_usize(signal, Arc::clone(&caught), signal as usize)
+            .with_context(|| format!("couldn't install handler for signal {signal}"))?;
     }
 
-    // Allow children to handle sigints
-    thread::sleep(Duration::from_millis(100));
+    // The fixed delay only exists for tasks without a readiness probe; skip it
+    // entirely when every task is gated on `ready-when` so probed configs don't
+    // pay the sleep on every run.
+    if children.iter().any(|bg| bg.ready_when.is_none()) {
+        info!(
+            seconds = args.sleep_after_spawn,
+            "waiting before running main task"
+        );
+        interruptible_sleep(Duration::from_secs_f64(args.sleep_after_spawn), &caught);
+    }
 
-    let outputs = children
-        .into_iter()
-        .filter_map(|(mut child, stdout_suffix, stderr_suffix)| {
-            let pid = child.id();
-            match child.kill() {
-                Ok(_) => {
-                    debug!(pid, "killed child");
-                    wait_or_log(child, stdout_suffix, stderr_suffix, pid)
-                }
-                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
-                    debug!(pid, "child already exited");
-                    wait_or_log(child, stdout_suffix, stderr_suffix, pid)
-                }
-                Err(error) => {
-                    error!(
-                        ?error,
-                        pid, "unable to kill child, output will not be collected"
-                    );
-                    None
+    let mut pending_error = None;
+    let main_output = if caught.load(Ordering::SeqCst) != 0 {
+        info!("interrupted during startup, skipping main task");
+        None
+    } else {
+        match await_readiness(&children, &caught) {
+            Ok(true) => {
+                info!(%cmd, ?cmd_args, "running main task");
+                match run_main_task(&cmd, cmd_args, &caught) {
+                    Ok(output) => {
+                        info!(?output, "main task exited");
+                        Some(output)
+                    }
+                    Err(error) => {
+                        error!(?error, "main task failed, collecting background output");
+                        pending_error = Some(error);
+                        None
+                    }
                 }
             }
-        });
+            Ok(false) => {
+                info!("interrupted before main task, collecting background output");
+                None
+            }
+            Err(error) => {
+                error!(?error, "readiness probe failed, collecting background output");
+                pending_error = Some(error);
+                None
+            }
+        }
+    };
+
+    let outputs = shutdown_and_collect(children);
 
     fs::create_dir_all(&args.outdir)
         .with_context(|| format!("couldn't create outdir {}", args.outdir.display()))?;
@@ -172,17 +490,129 @@ fn main() -> anyhow::Result<()> {
         false => args.name.unwrap_or_default(),
     };
 
-    for (output, stdout_suffix, stderr_suffix) in
-        iter::once((output, args.stdout_suffix, args.stderr_suffix)).chain(outputs)
+    for (output, stdout_suffix, stderr_suffix) in main_output
+        .map(|output| (output, args.stdout_suffix, args.stderr_suffix))
+        .into_iter()
+        .chain(outputs)
     {
         debug!(?output, ?stdout_suffix, ?stderr_suffix);
         write_or_log(stdout_suffix, &args.outdir, &name, &output.stdout);
         write_or_log(stderr_suffix, &args.outdir, &name, &output.stderr);
     }
 
+    if let Some(error) = pending_error {
+        return Err(error);
+    }
     Ok(())
 }
 
+/// Sleep for `duration`, returning early if an interrupt is caught so we don't
+/// keep waiting after the user has asked to stop.
+fn interruptible_sleep(duration: Duration, caught: &AtomicUsize) {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if caught.load(Ordering::SeqCst) != 0 {
+            return;
+        }
+        thread::sleep(SHUTDOWN_POLL_INTERVAL);
+    }
+}
+
+/// Block until every background task with a `ready-when` probe has matched it,
+/// erroring out if any task's `ready-timeout` elapses first.
+///
+/// Probes are checked by scanning the output buffers the forwarders are already
+/// filling, so this reuses the same incremental reads as streaming. Tasks
+/// without a probe are left to the plain `--sleep-after-spawn` delay.
+///
+/// Returns `Ok(true)` once every probe has matched, or `Ok(false)` if an
+/// interrupt is caught first so the caller can skip the main task and still
+/// collect output.
+fn await_readiness(children: &[Background], caught: &AtomicUsize) -> anyhow::Result<bool> {
+    let start = Instant::now();
+    let mut pending = children
+        .iter()
+        .filter_map(|bg| bg.ready_when.as_ref().map(|re| (bg, re, start + bg.ready_timeout)))
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(true);
+    }
+    info!(count = pending.len(), "waiting for background tasks to become ready");
+    while !pending.is_empty() {
+        if caught.load(Ordering::SeqCst) != 0 {
+            return Ok(false);
+        }
+        pending.retain(|(bg, pattern, _)| {
+            let ready = bg.is_ready(pattern);
+            if ready {
+                info!(cmd = %bg.cmd, "background task ready");
+            }
+            !ready
+        });
+        if let Some((bg, _, _)) = pending.iter().find(|(_, _, deadline)| Instant::now() >= *deadline)
+        {
+            anyhow::bail!(
+                "timed out waiting for background task `{}` to become ready",
+                bg.cmd
+            );
+        }
+        if !pending.is_empty() {
+            thread::sleep(READY_POLL_INTERVAL);
+        }
+    }
+    Ok(true)
+}
+
+/// Run the main task, capturing its output, while remaining responsive to
+/// `SIGINT`/`SIGTERM`.
+///
+/// If the user interrupts `meanwhile` the signal is forwarded to the main task
+/// and we keep waiting for it to exit, so the caller still reaches the normal
+/// shutdown-and-collect path and writes output files for every task up to the
+/// point of interruption instead of dying immediately.
+fn run_main_task(
+    cmd: &str,
+    args: Vec<String>,
+    caught: &AtomicUsize,
+) -> anyhow::Result<process::Output> {
+    let mut child = process::Command::new(cmd)
+        .args(args)
+        // Match the old `output()` semantics: the main task's stdin is closed.
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("couldn't execute main task with command {cmd}"))?;
+    let stdout = StreamCapture::spawn(child.stdout.take().unwrap(), format!("{cmd} (stdout)"), false);
+    let stderr = StreamCapture::spawn(child.stderr.take().unwrap(), format!("{cmd} (stderr)"), false);
+
+    let status = loop {
+        if let Some(status) = child.try_wait().context("couldn't wait on main task")? {
+            break status;
+        }
+        match caught.swap(0, Ordering::SeqCst) {
+            0 => thread::sleep(SHUTDOWN_POLL_INTERVAL),
+            signal => {
+                info!(signal, pid = child.id(), "interrupted, forwarding to main task");
+                if let Ok(signal) = Signal::try_from(signal as i32) {
+                    if let Err(errno) = nix::sys::signal::kill(
+                        nix::unistd::Pid::from_raw(child.id() as _),
+                        signal,
+                    ) {
+                        error!(%errno, pid = child.id(), "couldn't forward signal to main task");
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(process::Output {
+        status,
+        stdout: stdout.collect(),
+        stderr: stderr.collect(),
+    })
+}
+
 fn write_or_log(maybe_suffix: Option<String>, outdir: &PathBuf, name: &String, output: &Vec<u8>) {
     if let Some(suffix) = maybe_suffix {
         let file = outdir.join(format!("{name}{suffix}"));
@@ -192,23 +622,146 @@ fn write_or_log(maybe_suffix: Option<String>, outdir: &PathBuf, name: &String, o
     }
 }
 
-fn wait_or_log(
-    child: process::Child,
-    stdout_suffix: Option<String>,
-    stderr_suffix: Option<String>,
-    pid: u32,
-) -> Option<(process::Output, Option<String>, Option<String>)> {
-    match child.wait_with_output() {
-        Ok(output) => {
-            info!(?output, "captured background task output");
-            Some((output, stdout_suffix, stderr_suffix))
+/// Shut every background task down and collect its output.
+///
+/// Sends each task's `stop_signal` to all of them up front, then polls the whole
+/// set on a short interval, force-killing with `force_signal` any task that
+/// outlives its own `grace_period`. Signalling first and overlapping the grace
+/// periods — as a real process supervisor does — means unresponsive tasks stop
+/// in parallel rather than one grace period after another.
+fn shutdown_and_collect(
+    children: Vec<Background>,
+) -> Vec<(process::Output, Option<String>, Option<String>)> {
+    struct Pending {
+        bg: Background,
+        deadline: Instant,
+        forced: bool,
+    }
+
+    // Phase 1: ask every child to stop, recording its individual deadline.
+    let mut pending = children
+        .into_iter()
+        .map(|bg| {
+            send_signal(bg.pgid, bg.stop_signal);
+            let deadline = Instant::now() + bg.grace_period;
+            Pending {
+                bg,
+                deadline,
+                forced: false,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Phase 2: poll the whole set, escalating and collecting as each exits.
+    let mut collected = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let mut still = Vec::with_capacity(pending.len());
+        for mut p in pending {
+            let pid = p.bg.child.id();
+            match p.bg.child.try_wait() {
+                Ok(Some(status)) => {
+                    debug!(pid, ?status, "child exited");
+                    collected.push(collect_output(p.bg, status));
+                }
+                Ok(None) => {
+                    if !p.forced && Instant::now() >= p.deadline {
+                        debug!(pid, "grace period elapsed, forcing stop");
+                        send_signal(p.bg.pgid, p.bg.force_signal);
+                        p.forced = true;
+                    }
+                    still.push(p);
+                }
+                Err(error) => {
+                    error!(?error, pid, "unable to wait on child, forcing stop");
+                    send_signal(p.bg.pgid, p.bg.force_signal);
+                    match p.bg.child.wait() {
+                        Ok(status) => collected.push(collect_output(p.bg, status)),
+                        Err(error) => error!(
+                            ?error,
+                            pid, "unable to wait on child, output will not be collected"
+                        ),
+                    }
+                }
+            }
         }
-        Err(error) => {
-            error!(
-                ?error,
-                pid, "unable to wait on child, output will not be collected"
-            );
-            None
+        pending = still;
+        if !pending.is_empty() {
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
         }
     }
+    collected
+}
+
+/// Take an exited task's accumulated output. The forwarders have been filling
+/// their buffers since spawn, so joining them here reads nothing twice.
+fn collect_output(
+    bg: Background,
+    status: process::ExitStatus,
+) -> (process::Output, Option<String>, Option<String>) {
+    let output = process::Output {
+        status,
+        stdout: bg.stdout.map(StreamCapture::collect).unwrap_or_default(),
+        stderr: bg.stderr.map(StreamCapture::collect).unwrap_or_default(),
+    };
+    info!(?output, "captured background task output");
+    (output, bg.stdout_suffix, bg.stderr_suffix)
+}
+
+/// Send `signal` to an entire process group by its `pgid` (the child, which leads
+/// the group via `setsid`, and every descendant that has stayed in that group).
+fn send_signal(pgid: i32, signal: Signal) {
+    debug!(pgid, %signal, "signalling process group");
+    if let Err(errno) = nix::sys::signal::kill(nix::unistd::Pid::from_raw(-pgid), signal) {
+        error!(%errno, pgid, %signal, "couldn't signal process group");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emit_lines_advances_past_complete_lines_only() {
+        // Only "foo\n" and "bar\n" are complete; the trailing "ba" is retained.
+        assert_eq!(emit_lines("task", b"foo\nbar\nba", 0, false), 8);
+    }
+
+    #[test]
+    fn emit_lines_without_newline_emits_nothing_until_flush() {
+        assert_eq!(emit_lines("task", b"partial", 0, false), 0);
+        assert_eq!(emit_lines("task", b"partial", 0, true), 7);
+    }
+
+    #[test]
+    fn emit_lines_flush_drains_trailing_partial() {
+        assert_eq!(emit_lines("task", b"foo\nbar", 0, true), 7);
+    }
+
+    #[test]
+    fn output_matches_scans_lossy_utf8() {
+        let pattern = Regex::new("Listening on").unwrap();
+        assert!(output_matches(&pattern, b"\xffserver: Listening on :8080\n"));
+        assert!(!output_matches(&pattern, b"still starting up"));
+    }
+
+    #[test]
+    fn stdio_mode_deserializes_from_lowercase() {
+        #[derive(serde::Deserialize)]
+        struct Wrapper {
+            mode: StdioMode,
+        }
+        let inherit: Wrapper = toml::from_str(r#"mode = "inherit""#).unwrap();
+        let piped: Wrapper = toml::from_str(r#"mode = "piped""#).unwrap();
+        let null: Wrapper = toml::from_str(r#"mode = "null""#).unwrap();
+        assert!(matches!(inherit.mode, StdioMode::Inherit));
+        assert!(matches!(piped.mode, StdioMode::Piped));
+        assert!(matches!(null.mode, StdioMode::Null));
+    }
+
+    #[test]
+    fn stdio_mode_is_piped_only_for_piped() {
+        assert!(StdioMode::Piped.is_piped());
+        assert!(!StdioMode::Inherit.is_piped());
+        assert!(!StdioMode::Null.is_piped());
+    }
 }